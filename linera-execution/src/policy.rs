@@ -4,7 +4,7 @@
 //! This module contains types related to fees and pricing.
 
 use crate::{Message, Operation};
-use async_graphql::InputObject;
+use async_graphql::{InputObject, SimpleObject};
 use linera_base::data_types::{Amount, ArithmeticError};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -18,12 +18,13 @@ pub struct ResourceControlPolicy {
     pub fuel_unit: Amount,
     /// The price of one read operation.
     pub read_operation: Amount,
-    // TODO(#1530): Write operation.
+    /// The price of one write operation.
+    pub write_operation: Amount,
     /// The price of reading a byte.
     pub byte_read: Amount,
     /// The price to writting a byte
     pub byte_written: Amount,
-    /// The price of increasing storage by a byte.
+    /// The price of increasing storage by a byte (net growth only; overwrites are `byte_written`).
     pub byte_stored: Amount,
     /// The base price of adding an operation to a block.
     pub operation: Amount,
@@ -38,6 +39,20 @@ pub struct ResourceControlPolicy {
     pub maximum_bytes_read_per_block: u64,
     /// The maximum data to write per block
     pub maximum_bytes_written_per_block: u64,
+
+    /// The relative weight of each resource category under the compute-unit pricing mode.
+    pub compute_unit_weights: ComputeUnitWeights,
+    /// The price of a single compute unit, under the compute-unit pricing mode.
+    pub price_per_compute_unit: Amount,
+
+    /// The target number of bytes written per block, for the dynamic adjustment of `block`.
+    pub target_bytes_written_per_block: u64,
+    /// The denominator of the change applied to `block` in a single step: the step is
+    /// `block * |bytes_written - target| / target / max_base_fee_change_denominator`. Decreases
+    /// are thus at most `block / max_base_fee_change_denominator`; increases are not capped.
+    pub max_base_fee_change_denominator: u64,
+    /// The floor below which the dynamic adjustment will not lower `block`.
+    pub minimum_block_price: Amount,
 }
 
 impl Default for ResourceControlPolicy {
@@ -46,6 +61,7 @@ impl Default for ResourceControlPolicy {
             block: Amount::default(),
             fuel_unit: Amount::default(),
             read_operation: Amount::default(),
+            write_operation: Amount::default(),
             byte_read: Amount::default(),
             byte_written: Amount::default(),
             byte_stored: Amount::default(),
@@ -55,15 +71,69 @@ impl Default for ResourceControlPolicy {
             message_byte: Amount::default(),
             maximum_bytes_read_per_block: u64::MAX,
             maximum_bytes_written_per_block: u64::MAX,
+            compute_unit_weights: ComputeUnitWeights::default(),
+            price_per_compute_unit: Amount::default(),
+            target_bytes_written_per_block: 0,
+            max_base_fee_change_denominator: 8,
+            minimum_block_price: Amount::default(),
         }
     }
 }
 
+/// The relative cost, in abstract "compute units", of each resource category under the
+/// compute-unit pricing mode. See [`ResourceControlPolicy::compute_units`].
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Default, Serialize, Deserialize, InputObject)]
+pub struct ComputeUnitWeights {
+    pub fuel: u64,
+    pub read_operation: u64,
+    pub write_operation: u64,
+    pub byte_read: u64,
+    pub byte_written: u64,
+    pub byte_stored: u64,
+    pub operation: u64,
+    pub message: u64,
+}
+
 impl ResourceControlPolicy {
     pub fn block_price(&self) -> Amount {
         self.block
     }
 
+    /// Returns the `block` price that should apply to the next block, given the `block` price
+    /// used for the current one and how many bytes were written in it.
+    pub fn next_block_price(
+        &self,
+        current_block_price: Amount,
+        bytes_written_last_block: u64,
+    ) -> Result<Amount, PricingError> {
+        let target = self.target_bytes_written_per_block;
+        if target == 0 {
+            return Ok(current_block_price);
+        }
+        let denominator = u128::from(self.max_base_fee_change_denominator.max(1));
+        let scale = Amount::from_atto(u128::from(target).saturating_mul(denominator));
+        if bytes_written_last_block > target {
+            let excess = bytes_written_last_block - target;
+            let increase = Amount::from_atto(
+                current_block_price
+                    .try_mul(u128::from(excess))?
+                    .saturating_div(scale),
+            );
+            Ok(current_block_price.try_add(increase)?)
+        } else {
+            let shortfall = target - bytes_written_last_block;
+            let decrease = Amount::from_atto(
+                current_block_price
+                    .try_mul(u128::from(shortfall))?
+                    .saturating_div(scale),
+            );
+            let lowered = current_block_price
+                .try_sub(decrease)
+                .unwrap_or(self.minimum_block_price);
+            Ok(lowered.max(self.minimum_block_price))
+        }
+    }
+
     pub fn operation_price(&self, operation: &Operation) -> Result<Amount, PricingError> {
         match operation {
             Operation::System(_) => Ok(self.operation),
@@ -96,14 +166,20 @@ impl ResourceControlPolicy {
         Ok(self.read_operation.try_mul(count as u128)?)
     }
 
+    pub fn storage_num_writes_price(&self, count: u64) -> Result<Amount, PricingError> {
+        Ok(self.write_operation.try_mul(count as u128)?)
+    }
+
     pub fn storage_bytes_read_price(&self, count: u64) -> Result<Amount, PricingError> {
         Ok(self.byte_read.try_mul(count as u128)?)
     }
 
+    /// Prices the total number of bytes written, whether or not the key already existed.
     pub fn storage_bytes_written_price(&self, count: u64) -> Result<Amount, PricingError> {
         Ok(self.byte_written.try_mul(count as u128)?)
     }
 
+    /// Prices newly-allocated storage bytes only, i.e. `count` is the net growth in storage.
     pub fn storage_bytes_stored_price(&self, count: u64) -> Result<Amount, PricingError> {
         Ok(self.byte_stored.try_mul(count as u128)?)
     }
@@ -117,6 +193,84 @@ impl ResourceControlPolicy {
         u64::try_from(balance.saturating_div(self.fuel_unit)).unwrap_or(u64::MAX)
     }
 
+    /// Returns the number of compute units consumed by the given resource `usage`.
+    pub fn compute_units(&self, usage: &ResourceUsage) -> u64 {
+        let weights = &self.compute_unit_weights;
+        weights
+            .fuel
+            .saturating_mul(usage.fuel)
+            .saturating_add(weights.read_operation.saturating_mul(usage.read_operations))
+            .saturating_add(weights.write_operation.saturating_mul(usage.write_operations))
+            .saturating_add(weights.byte_read.saturating_mul(usage.bytes_read))
+            .saturating_add(weights.byte_written.saturating_mul(usage.bytes_written))
+            .saturating_add(weights.byte_stored.saturating_mul(usage.bytes_stored))
+            .saturating_add(weights.operation.saturating_mul(usage.operations.len() as u64))
+            .saturating_add(weights.message.saturating_mul(usage.messages.len() as u64))
+    }
+
+    /// Returns the fee for the given resource `usage` under the compute-unit pricing mode.
+    pub fn compute_unit_fee(&self, usage: &ResourceUsage) -> Result<Amount, PricingError> {
+        Ok(self
+            .price_per_compute_unit
+            .try_mul(u128::from(self.compute_units(usage)))?)
+    }
+
+    /// Estimates the fee for a block with the given resource `usage`, without executing it.
+    pub fn estimate_block_fee(&self, usage: &ResourceUsage) -> Result<FeeBreakdown, PricingError> {
+        let block = self.block_price();
+        let fuel = self.fuel_price(usage.fuel)?;
+        let read_operations = self.storage_num_reads_price(usage.read_operations)?;
+        let write_operations = self.storage_num_writes_price(usage.write_operations)?;
+        let bytes_read = self.storage_bytes_read_price(usage.bytes_read)?;
+        let bytes_written = self.storage_bytes_written_price(usage.bytes_written)?;
+        let bytes_stored = self.storage_bytes_stored_price(usage.bytes_stored)?;
+        let mut operations = Amount::default();
+        for operation in &usage.operations {
+            operations = operations.try_add(self.operation_price(operation)?)?;
+        }
+        let mut messages = Amount::default();
+        for message in &usage.messages {
+            messages = messages.try_add(self.message_price(message)?)?;
+        }
+        let total = block
+            .try_add(fuel)?
+            .try_add(read_operations)?
+            .try_add(write_operations)?
+            .try_add(bytes_read)?
+            .try_add(bytes_written)?
+            .try_add(bytes_stored)?
+            .try_add(operations)?
+            .try_add(messages)?;
+        let dominant_category = [
+            ("block", block),
+            ("fuel", fuel),
+            ("read_operations", read_operations),
+            ("write_operations", write_operations),
+            ("bytes_read", bytes_read),
+            ("bytes_written", bytes_written),
+            ("bytes_stored", bytes_stored),
+            ("operations", operations),
+            ("messages", messages),
+        ]
+        .into_iter()
+        .max_by_key(|(_, amount)| *amount)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_default();
+        Ok(FeeBreakdown {
+            block,
+            fuel,
+            read_operations,
+            write_operations,
+            bytes_read,
+            bytes_written,
+            bytes_stored,
+            operations,
+            messages,
+            total,
+            dominant_category,
+        })
+    }
+
     #[cfg(any(test, feature = "test"))]
     /// Creates a policy with no cost for anything except fuel.
     ///
@@ -148,6 +302,7 @@ impl ResourceControlPolicy {
         Self {
             block: Amount::from_milli(1),
             fuel_unit: Amount::from_atto(1_000_000_000),
+            write_operation: Amount::from_atto(10),
             byte_read: Amount::from_atto(100),
             byte_written: Amount::from_atto(1_000),
             operation: Amount::from_atto(10),
@@ -159,8 +314,362 @@ impl ResourceControlPolicy {
     }
 }
 
+/// A per-block resource budget that a block proposer can supply to cap spending along
+/// individual resource dimensions, rather than only capping the total fee.
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Default, Serialize, Deserialize, InputObject)]
+pub struct ResourceBounds {
+    /// The maximum amount of fuel that may be consumed.
+    pub max_fuel: Option<u64>,
+    /// The maximum number of bytes that may be read.
+    pub max_bytes_read: Option<u64>,
+    /// The maximum number of bytes that may be written.
+    pub max_bytes_written: Option<u64>,
+    /// The maximum number of bytes that may be added to storage.
+    pub max_bytes_stored: Option<u64>,
+    /// The maximum number of read operations that may be performed.
+    pub max_read_operations: Option<u64>,
+    /// The maximum total fee that may be charged.
+    pub max_fee: Option<Amount>,
+}
+
+/// A summary of the resources a block would consume, as input to
+/// [`ResourceControlPolicy::estimate_block_fee`].
+#[derive(Clone, Debug, Default)]
+pub struct ResourceUsage {
+    pub fuel: u64,
+    pub read_operations: u64,
+    pub write_operations: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub bytes_stored: u64,
+    pub operations: Vec<Operation>,
+    pub messages: Vec<Message>,
+}
+
+/// A per-category breakdown of a fee estimated by
+/// [`ResourceControlPolicy::estimate_block_fee`].
+#[derive(Eq, PartialEq, Clone, Debug, Default, Serialize, Deserialize, SimpleObject)]
+pub struct FeeBreakdown {
+    pub block: Amount,
+    pub fuel: Amount,
+    pub read_operations: Amount,
+    pub write_operations: Amount,
+    pub bytes_read: Amount,
+    pub bytes_written: Amount,
+    pub bytes_stored: Amount,
+    pub operations: Amount,
+    pub messages: Amount,
+    /// The sum of all the other fields.
+    pub total: Amount,
+    /// The name of the resource dimension that contributed the most to `total`.
+    pub dominant_category: String,
+}
+
+/// A tally of the resources actually consumed so far, to be checked against a
+/// [`ResourceBounds`] by a [`BoundsChecker`].
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Default)]
+pub struct ResourceTally {
+    pub fuel: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub bytes_stored: u64,
+    pub read_operations: u64,
+    pub fee: Amount,
+}
+
+/// Checks a [`ResourceTally`] against the tighter of the protocol-level ceilings in a
+/// [`ResourceControlPolicy`] and the proposer-supplied [`ResourceBounds`].
+#[derive(Clone, Debug)]
+pub struct BoundsChecker {
+    max_fuel: Option<u64>,
+    max_bytes_read: Option<u64>,
+    max_bytes_written: Option<u64>,
+    max_bytes_stored: Option<u64>,
+    max_read_operations: Option<u64>,
+    max_fee: Option<Amount>,
+}
+
+impl BoundsChecker {
+    /// Creates a checker that enforces the tighter of `policy`'s protocol-level ceilings and
+    /// `bounds`' proposer-supplied budget for each dimension.
+    pub fn new(policy: &ResourceControlPolicy, bounds: &ResourceBounds) -> Self {
+        Self {
+            max_fuel: bounds.max_fuel,
+            max_bytes_read: tighter(
+                bounds.max_bytes_read,
+                Some(policy.maximum_bytes_read_per_block),
+            ),
+            max_bytes_written: tighter(
+                bounds.max_bytes_written,
+                Some(policy.maximum_bytes_written_per_block),
+            ),
+            max_bytes_stored: bounds.max_bytes_stored,
+            max_read_operations: bounds.max_read_operations,
+            max_fee: bounds.max_fee,
+        }
+    }
+
+    /// Checks `tally` against every dimension, returning the first violation encountered.
+    pub fn check(&self, tally: &ResourceTally) -> Result<(), PricingError> {
+        if let Some(max) = self.max_fuel {
+            if tally.fuel > max {
+                return Err(PricingError::MaxFuelExceeded {
+                    max,
+                    actual: tally.fuel,
+                });
+            }
+        }
+        if let Some(max) = self.max_bytes_read {
+            if tally.bytes_read > max {
+                return Err(PricingError::MaxBytesReadExceeded {
+                    max,
+                    actual: tally.bytes_read,
+                });
+            }
+        }
+        if let Some(max) = self.max_bytes_written {
+            if tally.bytes_written > max {
+                return Err(PricingError::MaxBytesWrittenExceeded {
+                    max,
+                    actual: tally.bytes_written,
+                });
+            }
+        }
+        if let Some(max) = self.max_bytes_stored {
+            if tally.bytes_stored > max {
+                return Err(PricingError::MaxBytesStoredExceeded {
+                    max,
+                    actual: tally.bytes_stored,
+                });
+            }
+        }
+        if let Some(max) = self.max_read_operations {
+            if tally.read_operations > max {
+                return Err(PricingError::MaxReadOperationsExceeded {
+                    max,
+                    actual: tally.read_operations,
+                });
+            }
+        }
+        if let Some(max) = self.max_fee {
+            if tally.fee > max {
+                return Err(PricingError::MaxFeeExceeded {
+                    max,
+                    actual: tally.fee,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the tighter (smaller) of two optional limits, treating `None` as unbounded.
+fn tighter(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum PricingError {
     #[error(transparent)]
     ArithmeticError(#[from] ArithmeticError),
+    #[error("Maximum fuel exceeded: allowed {max}, actual {actual}")]
+    MaxFuelExceeded { max: u64, actual: u64 },
+    #[error("Maximum bytes read exceeded: allowed {max}, actual {actual}")]
+    MaxBytesReadExceeded { max: u64, actual: u64 },
+    #[error("Maximum bytes written exceeded: allowed {max}, actual {actual}")]
+    MaxBytesWrittenExceeded { max: u64, actual: u64 },
+    #[error("Maximum bytes stored exceeded: allowed {max}, actual {actual}")]
+    MaxBytesStoredExceeded { max: u64, actual: u64 },
+    #[error("Maximum read operations exceeded: allowed {max}, actual {actual}")]
+    MaxReadOperationsExceeded { max: u64, actual: u64 },
+    #[error("Maximum fee exceeded: allowed {max}, actual {actual}")]
+    MaxFeeExceeded { max: Amount, actual: Amount },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_num_writes_price_is_linear_in_count() {
+        let policy = ResourceControlPolicy {
+            write_operation: Amount::from_atto(10),
+            ..ResourceControlPolicy::default()
+        };
+        assert_eq!(
+            policy.storage_num_writes_price(3).unwrap(),
+            Amount::from_atto(30)
+        );
+    }
+
+    #[test]
+    fn byte_stored_and_byte_written_do_not_double_charge_on_overwrite() {
+        // A write of 100 bytes to a key that already held 40 bytes: the total bytes written is
+        // 100, but only the 60 new bytes grow storage.
+        let policy = ResourceControlPolicy {
+            byte_written: Amount::from_atto(1),
+            byte_stored: Amount::from_atto(5),
+            ..ResourceControlPolicy::default()
+        };
+        let total_bytes_written = 100;
+        let new_bytes_stored = 60;
+        let written_price = policy
+            .storage_bytes_written_price(total_bytes_written)
+            .unwrap();
+        let stored_price = policy
+            .storage_bytes_stored_price(new_bytes_stored)
+            .unwrap();
+        assert_eq!(written_price, Amount::from_atto(100));
+        assert_eq!(stored_price, Amount::from_atto(300));
+        // The overwritten 40 bytes are billed once, under `byte_written` only.
+        assert_eq!(
+            written_price.try_add(stored_price).unwrap(),
+            Amount::from_atto(400)
+        );
+    }
+
+    #[test]
+    fn next_block_price_raise_is_not_capped_at_one_target_of_excess() {
+        let policy = ResourceControlPolicy {
+            target_bytes_written_per_block: 100,
+            max_base_fee_change_denominator: 8,
+            ..ResourceControlPolicy::default()
+        };
+        let current = Amount::from_atto(800);
+        // Usage is 4 targets above target, so the excess is far more than one target's worth.
+        let next = policy.next_block_price(current, 500).unwrap();
+        // increase = current * (500 - 100) / 100 / 8 = current * 400 / 800 = current / 2 = 400
+        assert_eq!(next, Amount::from_atto(1_200));
+    }
+
+    #[test]
+    fn next_block_price_lowers_towards_floor_when_usage_is_below_target() {
+        let policy = ResourceControlPolicy {
+            target_bytes_written_per_block: 100,
+            max_base_fee_change_denominator: 8,
+            minimum_block_price: Amount::from_atto(10),
+            ..ResourceControlPolicy::default()
+        };
+        let current = Amount::from_atto(800);
+        let next = policy.next_block_price(current, 0).unwrap();
+        // decrease = current * 100 / 100 / 8 = current / 8 = 100
+        assert_eq!(next, Amount::from_atto(700));
+    }
+
+    #[test]
+    fn compute_units_sums_weighted_categories() {
+        let policy = ResourceControlPolicy {
+            compute_unit_weights: ComputeUnitWeights {
+                fuel: 1,
+                read_operation: 2,
+                write_operation: 4,
+                byte_written: 3,
+                ..ComputeUnitWeights::default()
+            },
+            ..ResourceControlPolicy::default()
+        };
+        let usage = ResourceUsage {
+            fuel: 10,
+            read_operations: 5,
+            write_operations: 2,
+            bytes_written: 7,
+            ..ResourceUsage::default()
+        };
+        assert_eq!(policy.compute_units(&usage), 10 * 1 + 5 * 2 + 2 * 4 + 7 * 3);
+    }
+
+    #[test]
+    fn estimate_block_fee_includes_write_operations() {
+        let policy = ResourceControlPolicy::all_categories();
+        let usage = ResourceUsage {
+            write_operations: 3,
+            ..ResourceUsage::default()
+        };
+        let breakdown = policy.estimate_block_fee(&usage).unwrap();
+        assert_eq!(
+            breakdown.write_operations,
+            policy.storage_num_writes_price(3).unwrap()
+        );
+        assert_eq!(breakdown.total, policy.block.try_add(breakdown.write_operations).unwrap());
+    }
+
+    #[test]
+    fn estimate_block_fee_dominant_category_picks_largest_contributor() {
+        let policy = ResourceControlPolicy::all_categories();
+        // 2_000_000_000_000 bytes at 1_000 atto each cost 0.002, more than the 0.001 block price.
+        let usage = ResourceUsage {
+            bytes_written: 2_000_000_000_000,
+            ..ResourceUsage::default()
+        };
+        let breakdown = policy.estimate_block_fee(&usage).unwrap();
+        assert_eq!(breakdown.dominant_category, "bytes_written");
+    }
+
+    #[test]
+    fn estimate_block_fee_dominant_category_defaults_to_messages_when_all_zero() {
+        let policy = ResourceControlPolicy::default();
+        let usage = ResourceUsage::default();
+        let breakdown = policy.estimate_block_fee(&usage).unwrap();
+        assert_eq!(breakdown.total, Amount::default());
+        assert_eq!(breakdown.dominant_category, "messages");
+    }
+
+    #[test]
+    fn bounds_checker_allows_tally_within_bounds() {
+        let policy = ResourceControlPolicy::default();
+        let bounds = ResourceBounds {
+            max_fuel: Some(10),
+            max_bytes_written: Some(100),
+            ..ResourceBounds::default()
+        };
+        let checker = BoundsChecker::new(&policy, &bounds);
+        let tally = ResourceTally {
+            fuel: 10,
+            bytes_written: 100,
+            ..ResourceTally::default()
+        };
+        assert!(checker.check(&tally).is_ok());
+    }
+
+    #[test]
+    fn bounds_checker_rejects_tally_exceeding_a_bound() {
+        let policy = ResourceControlPolicy::default();
+        let bounds = ResourceBounds {
+            max_fuel: Some(10),
+            ..ResourceBounds::default()
+        };
+        let checker = BoundsChecker::new(&policy, &bounds);
+        let tally = ResourceTally {
+            fuel: 11,
+            ..ResourceTally::default()
+        };
+        assert!(matches!(
+            checker.check(&tally),
+            Err(PricingError::MaxFuelExceeded { max: 10, actual: 11 })
+        ));
+    }
+
+    #[test]
+    fn bounds_checker_uses_the_tighter_of_policy_ceiling_and_proposer_bound() {
+        let policy = ResourceControlPolicy {
+            maximum_bytes_written_per_block: 50,
+            ..ResourceControlPolicy::default()
+        };
+        let bounds = ResourceBounds {
+            max_bytes_written: Some(1_000),
+            ..ResourceBounds::default()
+        };
+        let checker = BoundsChecker::new(&policy, &bounds);
+        let tally = ResourceTally {
+            bytes_written: 60,
+            ..ResourceTally::default()
+        };
+        assert!(matches!(
+            checker.check(&tally),
+            Err(PricingError::MaxBytesWrittenExceeded { max: 50, actual: 60 })
+        ));
+    }
 }